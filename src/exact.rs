@@ -0,0 +1,53 @@
+use crate::system::{compute_cost, DistanceSource};
+use anyhow::{anyhow, Error};
+use permutohedron::LexicalPermutation;
+
+/// Above this many cities the `(n-1)!` permutation space is no longer
+/// practical to enumerate exhaustively.
+pub const MAX_CITIES: usize = 11;
+
+/// Find the true optimum by enumerating every permutation of the cities
+/// other than `initial`, in place via lexicographic permutation, scoring
+/// each with `compute_cost`. Useful to measure the optimality gap of the
+/// ACS metaheuristic on small instances.
+pub fn solve(distances: &DistanceSource, initial: usize) -> Result<(Vec<usize>, f64), Error> {
+    let no_cities = distances.size();
+    if no_cities > MAX_CITIES {
+        return Err(anyhow!(
+            "exact solver only supports up to {} cities, got {}",
+            MAX_CITIES,
+            no_cities
+        ));
+    }
+
+    if initial >= no_cities {
+        return Err(anyhow!(
+            "initial city {} is out of bounds for {} cities",
+            initial,
+            no_cities
+        ));
+    }
+
+    let mut rest: Vec<usize> = (0..no_cities).filter(|&city| city != initial).collect();
+    rest.sort_unstable();
+
+    let mut tour = Vec::with_capacity(no_cities);
+    tour.push(initial);
+    tour.extend_from_slice(&rest);
+
+    let mut best_cost = compute_cost(&tour, distances);
+    let mut best_tour = tour.clone();
+
+    while rest.next_permutation() {
+        tour.truncate(1);
+        tour.extend_from_slice(&rest);
+
+        let cost = compute_cost(&tour, distances);
+        if cost < best_cost {
+            best_cost = cost;
+            best_tour = tour.clone();
+        }
+    }
+
+    Ok((best_tour, best_cost))
+}
@@ -0,0 +1,193 @@
+use crate::system::{AntProps, AntSystem, DistanceSource};
+use anyhow::{anyhow, Error};
+use ndarray::Array2;
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "ant-colony-system", about = "Ant colony system TSP solver")]
+pub struct Opts {
+    /// Path to a CSV/TSV file holding the (square) distance matrix.
+    /// Mutually exclusive with `--coords`
+    #[structopt(long, parse(from_os_str))]
+    pub path: Option<PathBuf>,
+
+    /// Path to a CSV/TSV file holding 2D city coordinates ("x,y" per line).
+    /// Distances are derived from the Euclidean distance between points and
+    /// an R-tree candidate list is built for each city. Mutually exclusive
+    /// with `--path`
+    #[structopt(long, parse(from_os_str))]
+    pub coords: Option<PathBuf>,
+
+    /// Number of nearest neighbours to keep per city when `--coords` is used
+    #[structopt(long = "k-nearest", default_value = "5")]
+    pub k_nearest: usize,
+
+    /// Number of ants in the colony
+    #[structopt(long, default_value = "10")]
+    pub size: usize,
+
+    /// Number of iterations to run
+    #[structopt(long, default_value = "100")]
+    pub iters: usize,
+
+    /// Initial city, given as a 0-based index
+    #[structopt(long, default_value = "0")]
+    pub initial: usize,
+
+    #[structopt(long, default_value = "1.0")]
+    pub alpha: f64,
+
+    #[structopt(long, default_value = "1.0")]
+    pub beta: f64,
+
+    #[structopt(long, default_value = "0.5")]
+    pub rho: f64,
+
+    #[structopt(long, default_value = "1.0")]
+    pub q: f64,
+
+    #[structopt(long, default_value = "0.5")]
+    pub q0: f64,
+
+    #[structopt(long, default_value = "0.5")]
+    pub phi: f64,
+
+    #[structopt(long = "initial-pheromone", default_value = "0.1")]
+    pub initial_pheromone: f64,
+
+    /// Run for this many seconds instead of a fixed number of iterations
+    #[structopt(long = "time-limit")]
+    pub time_limit: Option<u64>,
+
+    /// Also compute the true optimum with a brute-force solver, for
+    /// instances small enough for it to be practical, and report the gap
+    /// against the ACS best
+    #[structopt(long)]
+    pub exact: bool,
+}
+
+/// Build the `AntSystem` the CLI asked for, either from a distance matrix
+/// (`--path`) or from 2D coordinates (`--coords`).
+pub fn build_ant_system(opts: &Opts) -> Result<AntSystem, Error> {
+    validate_at_least_one(opts.size, "--size")?;
+    validate_at_least_one(opts.iters, "--iters")?;
+
+    let props = AntProps {
+        alpha: opts.alpha,
+        beta: opts.beta,
+        rho: opts.rho,
+        q: opts.q,
+        q0: opts.q0,
+        phi: opts.phi,
+        initial_pheromone: opts.initial_pheromone,
+        distances: DistanceSource::Matrix(Array2::zeros((0, 0))),
+    };
+
+    match (&opts.path, &opts.coords) {
+        (Some(_), Some(_)) => Err(anyhow!("--path and --coords are mutually exclusive")),
+        (None, None) => Err(anyhow!("one of --path or --coords is required")),
+        (Some(path), None) => {
+            let distances = load_distance_matrix(path)?;
+            validate_initial(opts.initial, distances.shape()[0])?;
+
+            Ok(AntSystem::new(
+                opts.size,
+                opts.initial,
+                AntProps {
+                    distances: DistanceSource::Matrix(distances),
+                    ..props
+                },
+            ))
+        }
+        (None, Some(coords_path)) => {
+            let coords = load_coordinates(coords_path)?;
+            validate_initial(opts.initial, coords.len())?;
+
+            Ok(AntSystem::from_coordinates(
+                opts.size,
+                opts.initial,
+                opts.k_nearest,
+                coords,
+                props,
+            ))
+        }
+    }
+}
+
+fn validate_initial(initial: usize, size: usize) -> Result<(), Error> {
+    if initial >= size {
+        return Err(anyhow!(
+            "--initial {} is out of bounds for {} cities",
+            initial,
+            size
+        ));
+    }
+
+    Ok(())
+}
+
+fn validate_at_least_one(value: usize, flag: &str) -> Result<(), Error> {
+    if value == 0 {
+        return Err(anyhow!("{} must be at least 1", flag));
+    }
+
+    Ok(())
+}
+
+fn load_distance_matrix(path: &PathBuf) -> Result<Array2<f64>, Error> {
+    let content = std::fs::read_to_string(path)?;
+
+    let rows: Vec<Vec<f64>> = content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            line.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .map(|field| field.parse::<f64>().map_err(Error::from))
+                .collect()
+        })
+        .collect::<Result<_, Error>>()?;
+
+    let size = rows.len();
+    if size == 0 {
+        return Err(anyhow!("distance matrix at {:?} is empty", path));
+    }
+
+    if rows.iter().any(|row| row.len() != size) {
+        return Err(anyhow!(
+            "distance matrix at {:?} is not square ({}x{} expected)",
+            path,
+            size,
+            size
+        ));
+    }
+
+    let flat: Vec<f64> = rows.into_iter().flatten().collect();
+    Array2::from_shape_vec((size, size), flat).map_err(Error::from)
+}
+
+fn load_coordinates(path: &PathBuf) -> Result<Vec<[f64; 2]>, Error> {
+    let content = std::fs::read_to_string(path)?;
+
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| {
+            let fields: Vec<f64> = line
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|field| !field.is_empty())
+                .map(|field| field.parse::<f64>().map_err(Error::from))
+                .collect::<Result<_, Error>>()?;
+
+            match fields[..] {
+                [x, y] => Ok([x, y]),
+                _ => Err(anyhow!(
+                    "expected 2 coordinates per line in {:?}, got {}",
+                    path,
+                    fields.len()
+                )),
+            }
+        })
+        .collect()
+}
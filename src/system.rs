@@ -1,8 +1,11 @@
-use crate::utils::{ToCharIndex, ToDisplayPath};
+use crate::utils::{pretty_matrix, ToCharIndex, ToDisplayPath};
 use anyhow::Error;
 use ndarray::{Array2, Ix2, ShapeBuilder};
 use rand::{thread_rng, Rng};
+use rayon::prelude::*;
+use rstar::{PointDistance, RTree, RTreeObject, AABB};
 use std::io::Write;
+use std::time::{Duration, Instant};
 
 fn init_pheromone_matrix<S>(shape: S, value: f64) -> Array2<f64>
 where
@@ -11,14 +14,100 @@ where
     Array2::from_shape_fn(shape, |(i, j)| if i == j { 0.0 } else { value })
 }
 
-fn compute_visiblity_matrix(distances: &Array2<f64>) -> Array2<f64> {
-    distances.mapv(|v| 1.0 / v)
+/// Above this many cities a dense preview matrix is no longer cheap enough
+/// to build just for logging, so it's skipped instead.
+const MAX_PREVIEW_CITIES: usize = 200;
+
+/// Where distances between cities come from: a pre-computed matrix, or a
+/// set of 2D coordinates, in which case distances are derived on demand
+/// instead of materializing a dense `n x n` matrix up front.
+#[derive(Debug, Clone)]
+pub enum DistanceSource {
+    Matrix(Array2<f64>),
+    Coordinates(Vec<[f64; 2]>),
 }
 
-fn compute_cost(solution: &[usize], distances: &Array2<f64>) -> f64 {
+impl DistanceSource {
+    pub fn size(&self) -> usize {
+        match self {
+            DistanceSource::Matrix(matrix) => matrix.shape()[0],
+            DistanceSource::Coordinates(coords) => coords.len(),
+        }
+    }
+
+    pub fn distance(&self, from: usize, to: usize) -> f64 {
+        match self {
+            DistanceSource::Matrix(matrix) => matrix[[from, to]],
+            DistanceSource::Coordinates(coords) => {
+                let dx = coords[from][0] - coords[to][0];
+                let dy = coords[from][1] - coords[to][1];
+                (dx * dx + dy * dy).sqrt()
+            }
+        }
+    }
+
+    pub fn visibility(&self, from: usize, to: usize) -> f64 {
+        1.0 / self.distance(from, to)
+    }
+}
+
+impl Default for DistanceSource {
+    fn default() -> Self {
+        DistanceSource::Matrix(Array2::zeros((0, 0)))
+    }
+}
+
+pub(crate) fn compute_cost(solution: &[usize], distances: &DistanceSource) -> f64 {
     solution
         .windows(2)
-        .fold(0.0, |acc, edge| acc + distances[[edge[0], edge[1]]])
+        .fold(0.0, |acc, edge| acc + distances.distance(edge[0], edge[1]))
+}
+
+/// A city located at a 2D point, indexable so it can be looked back up once
+/// pulled out of the `RTree`.
+struct CityPoint {
+    index: usize,
+    coords: [f64; 2],
+}
+
+impl RTreeObject for CityPoint {
+    type Envelope = AABB<[f64; 2]>;
+
+    fn envelope(&self) -> Self::Envelope {
+        AABB::from_point(self.coords)
+    }
+}
+
+impl PointDistance for CityPoint {
+    fn distance_2(&self, point: &[f64; 2]) -> f64 {
+        let dx = self.coords[0] - point[0];
+        let dy = self.coords[1] - point[1];
+        dx * dx + dy * dy
+    }
+}
+
+/// For every city, find its `k` nearest neighbours using an `RTree` so the
+/// selection loops can try a short candidate list before falling back to a
+/// full scan.
+fn build_candidate_lists(coords: &[[f64; 2]], k: usize) -> Vec<Vec<usize>> {
+    let points: Vec<_> = coords
+        .iter()
+        .enumerate()
+        .map(|(index, &coords)| CityPoint { index, coords })
+        .collect();
+    let tree = RTree::bulk_load(points);
+
+    coords
+        .iter()
+        .enumerate()
+        .map(|(index, point)| {
+            tree.nearest_neighbor_iter(point)
+                .filter(|city| city.index != index)
+                .take(k)
+                .map(|city| city.index)
+                .collect()
+        })
+        .collect()
 }
 
 #[derive(Debug, Clone, Default)]
@@ -33,12 +122,17 @@ pub struct AntSystem {
     pub size: usize,
     pub initial: usize,
 
-    pub distances: Array2<f64>,
-    pub visibility: Array2<f64>,
+    pub distances: DistanceSource,
     pub pheromones: Array2<f64>,
     pub initial_pheromone: f64,
 
     pub best_solution: Vec<usize>,
+
+    /// For each city, its `k` nearest neighbours, used as the first place to
+    /// look for the next city to visit. Empty when the colony was built from
+    /// a plain distance matrix, in which case selection always falls back to
+    /// a full scan.
+    pub candidates: Vec<Vec<usize>>,
 }
 
 pub struct AntProps {
@@ -49,15 +143,14 @@ pub struct AntProps {
     pub q0: f64,
     pub phi: f64,
     pub initial_pheromone: f64,
-    pub distances: Array2<f64>,
+    pub distances: DistanceSource,
 }
 
 impl AntSystem {
     pub fn new(size: usize, initial: usize, props: AntProps) -> Self {
-        let shape = props.distances.raw_dim();
+        let no_cities = props.distances.size();
 
-        let pheromones = init_pheromone_matrix(shape, props.initial_pheromone);
-        let visibility = compute_visiblity_matrix(&props.distances);
+        let pheromones = init_pheromone_matrix((no_cities, no_cities), props.initial_pheromone);
         let distances = props.distances;
 
         Self {
@@ -70,19 +163,74 @@ impl AntSystem {
             size,
             initial,
             distances,
-            visibility,
             pheromones,
             initial_pheromone: props.initial_pheromone,
             best_solution: Vec::new(),
+            candidates: Vec::new(),
         }
     }
 
+    /// Build a colony from 2D city coordinates instead of a pre-computed
+    /// distance matrix. Distances/visibility are derived from the Euclidean
+    /// distance between points on demand, instead of materializing a dense
+    /// `n x n` distance matrix; the pheromone matrix is still a dense
+    /// `n x n` matrix, updated cell by cell after every iteration.
+    /// Each city also gets a candidate list of its `k` nearest neighbours
+    /// (via an `RTree`) so the selection loops can skip the full `O(n)`
+    /// scan on large instances.
+    pub fn from_coordinates(
+        size: usize,
+        initial: usize,
+        k: usize,
+        coords: Vec<[f64; 2]>,
+        props: AntProps,
+    ) -> Self {
+        let candidates = build_candidate_lists(&coords, k);
+
+        let mut this = Self::new(
+            size,
+            initial,
+            AntProps {
+                distances: DistanceSource::Coordinates(coords),
+                ..props
+            },
+        );
+        this.candidates = candidates;
+        this
+    }
+
+    /// A dense preview of the visibility matrix for logging, or `None` when
+    /// the instance is too large to materialize one cheaply.
+    pub fn visibility_preview(&self) -> Option<Array2<f64>> {
+        let no_cities = self.distances.size();
+        if no_cities > MAX_PREVIEW_CITIES {
+            return None;
+        }
+
+        Some(Array2::from_shape_fn((no_cities, no_cities), |(i, j)| {
+            self.distances.visibility(i, j)
+        }))
+    }
+
     pub fn run<W: Write>(&mut self, out: &mut W) -> Result<Vec<(Vec<usize>, f64)>, Error> {
-        let mut solutions = Vec::new();
+        // Ants only read the pheromone snapshot and buffer their local
+        // updates, so they can be built concurrently; buffers are applied
+        // and logs flushed afterwards, in ant order.
+        let built: Vec<_> = (0..self.size)
+            .into_par_iter()
+            .map(|ant| self.build_solution(ant))
+            .collect::<Result<_, Error>>()?;
+
+        let mut local_updates = Vec::new();
+        for (_, edges, log) in &built {
+            out.write_all(log)?;
+            local_updates.extend(edges.iter().copied());
+        }
 
-        for ant in 0..self.size {
-            let solution = self.build_solution(ant, out)?;
-            solutions.push(solution);
+        for (from, to) in local_updates {
+            let pheromone = self.pheromones[[from, to]];
+            self.pheromones[[from, to]] =
+                (1.0 - self.phi) * pheromone + self.phi * self.initial_pheromone;
         }
 
         let mut solutions_to_return = Vec::new();
@@ -92,7 +240,7 @@ impl AntSystem {
             compute_cost(&self.best_solution, &self.distances)
         };
 
-        for (ant, solution) in solutions.into_iter().enumerate() {
+        for (ant, (solution, _, _)) in built.into_iter().enumerate() {
             let cost = compute_cost(&solution, &self.distances);
             writeln!(
                 out,
@@ -110,6 +258,10 @@ impl AntSystem {
             solutions_to_return.push((solution, cost));
         }
 
+        let mut best_solution = std::mem::take(&mut self.best_solution);
+        Self::local_search(&self.distances, &mut best_solution, out)?;
+        self.best_solution = best_solution;
+
         let best_cost = compute_cost(&self.best_solution, &self.distances);
         writeln!(
             out,
@@ -120,27 +272,112 @@ impl AntSystem {
         self.update_pheromones(out)?;
         Ok(solutions_to_return)
     }
+
+    /// Run whole iterations back to back until `budget` elapses, tracking the
+    /// global best exactly as a fixed-iteration loop would. The deadline is
+    /// only checked between iterations, so a partially completed iteration
+    /// never corrupts the best solution found so far.
+    pub fn run_within<W: Write>(
+        &mut self,
+        budget: Duration,
+        out: &mut W,
+    ) -> Result<(Vec<usize>, f64), Error> {
+        let deadline = Instant::now() + budget;
+        let mut best: Option<(Vec<usize>, f64)> = None;
+        let mut iteration = 0;
+
+        loop {
+            iteration += 1;
+
+            writeln!(out, "------------------------------------")?;
+            writeln!(out, "Iteración {}\n", iteration)?;
+
+            match self.visibility_preview() {
+                Some(matrix) => writeln!(
+                    out,
+                    "Matriz de visibilidad:\n{}",
+                    pretty_matrix(&matrix, 6)
+                )?,
+                None => writeln!(
+                    out,
+                    "Matriz de visibilidad: omitida ({} ciudades)\n",
+                    self.distances.size()
+                )?,
+            }
+
+            writeln!(
+                out,
+                "Matriz de feromonas:\n{}",
+                pretty_matrix(&self.pheromones, 6)
+            )?;
+
+            let solutions_w_costs = self.run(out)?;
+            let min = solutions_w_costs
+                .into_iter()
+                .min_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap())
+                .unwrap();
+
+            writeln!(
+                out,
+                "Mejor camino en esta iteración: {} con costo {}\n",
+                min.0.to_display_path()?,
+                min.1
+            )?;
+
+            match &mut best {
+                Some(best) if min.1 < best.1 => *best = min,
+                Some(_) => {}
+                None => best = Some(min),
+            }
+
+            if Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        Ok(best.expect("at least one iteration always runs before the deadline is checked"))
+    }
 }
 
 impl AntSystem {
-    fn intesification<W>(&mut self, visited: &mut Vec<usize>, out: &mut W) -> Result<(), Error>
+    /// Cities worth considering as the next hop from `curr`: the unvisited
+    /// entries of its candidate list, or every unvisited city when there is
+    /// no candidate list (plain distance-matrix colonies) or every candidate
+    /// has already been visited.
+    fn reachable_cities(&self, curr: usize, visited: &[usize]) -> Vec<usize> {
+        let no_cities = self.distances.size();
+
+        let from_candidates: Vec<usize> = self
+            .candidates
+            .get(curr)
+            .into_iter()
+            .flatten()
+            .copied()
+            .filter(|city| !visited.contains(city))
+            .collect();
+
+        if !from_candidates.is_empty() {
+            return from_candidates;
+        }
+
+        (0..no_cities).filter(|city| !visited.contains(city)).collect()
+    }
+
+    // Returns the chosen `(curr, choosen)` arc; the caller applies its local
+    // pheromone update once the colony has been built.
+    fn intesification<W>(&self, visited: &[usize], out: &mut W) -> Result<(usize, usize), Error>
     where
         W: Write,
     {
-        let no_cities = self.visibility.shape()[0];
         let curr = *visited.last().expect("No cities visited?");
+        let reachable = self.reachable_cities(curr, visited);
 
         let mut values = Vec::new();
 
-        // Iterate over all the cities
-        for city in 0..no_cities {
-            // And skip already visited cities
-            if visited.contains(&city) {
-                continue;
-            }
-
+        // Iterate over the reachable cities
+        for city in reachable {
             let pheromone = self.pheromones[[curr, city]];
-            let visibility = self.visibility[[curr, city]].powf(self.beta);
+            let visibility = self.distances.visibility(curr, city).powf(self.beta);
             let prod = pheromone * visibility;
 
             writeln!(
@@ -163,55 +400,47 @@ impl AntSystem {
             .expect("No cities-values (diversification)?");
 
         writeln!(out, "Siguiente ciudad: {}", choosen.to_char_index())?;
-        visited.push(choosen);
 
-        // Update the arc "curr <-> choosen"
         let pheromone = self.pheromones[[curr, choosen]];
-        self.pheromones[[curr, choosen]] =
-            (1.0 - self.phi) * pheromone + self.phi * self.initial_pheromone;
+        let updated = (1.0 - self.phi) * pheromone + self.phi * self.initial_pheromone;
 
         writeln!(
             out,
-            "Actualizaci贸n feromona de arco {} -> {} = (1 - ) * {} +  * {} = {}\n",
+            "Actualización feromona de arco {} -> {} = (1 - ) * {} +  * {} = {} (aplicada al finalizar la colonia)\n",
             curr.to_char_index(),
             choosen.to_char_index(),
             pheromone,
             self.initial_pheromone,
-            self.pheromones[[curr, choosen]]
+            updated
         )?;
 
-        Ok(())
+        Ok((curr, choosen))
     }
 
-    fn diversification<W>(&mut self, visited: &mut Vec<usize>, out: &mut W) -> Result<(), Error>
+    // Same contract as `intesification`, but picking the next city by
+    // roulette over the transition probabilities.
+    fn diversification<W>(&self, visited: &[usize], out: &mut W) -> Result<(usize, usize), Error>
     where
         W: Write,
     {
-        let no_cities = self.visibility.shape()[0];
         let curr = *visited.last().expect("No cities visited?");
+        let reachable = self.reachable_cities(curr, visited);
 
         let mut probs = Vec::new();
 
         // Sum to create the denominator
-        let sum = (0..no_cities)
-            .filter(|city| !visited.contains(city))
-            .fold(0.0, |acc, city| {
-                let pheromone = self.pheromones[[curr, city]];
-                let visibility = self.visibility[[curr, city]];
-
-                acc + pheromone.powf(self.alpha) * visibility.powf(self.beta)
-            });
-
-        // Iterate over all the cities
-        for city in 0..no_cities {
-            // And skip already visited cities
-            if visited.contains(&city) {
-                continue;
-            }
+        let sum = reachable.iter().fold(0.0, |acc, &city| {
+            let pheromone = self.pheromones[[curr, city]];
+            let visibility = self.distances.visibility(curr, city);
+
+            acc + pheromone.powf(self.alpha) * visibility.powf(self.beta)
+        });
 
+        // Iterate over the reachable cities
+        for city in reachable {
             // Calculate the probability for a this city
             let pheromone = self.pheromones[[curr, city]].powf(self.alpha);
-            let visibility = self.visibility[[curr, city]].powf(self.beta);
+            let visibility = self.distances.visibility(curr, city).powf(self.beta);
             let prod = pheromone * visibility;
             let prob = prod / sum;
 
@@ -256,32 +485,33 @@ impl AntSystem {
         }
 
         writeln!(out, "Siguiente ciudad: {}", choosen.to_char_index())?;
-        visited.push(choosen);
 
-        // Update the arc "curr <-> choosen"
         let pheromone = self.pheromones[[curr, choosen]];
-        self.pheromones[[curr, choosen]] =
-            (1.0 - self.phi) * pheromone + self.phi * self.initial_pheromone;
+        let updated = (1.0 - self.phi) * pheromone + self.phi * self.initial_pheromone;
 
         writeln!(
             out,
-            "Actualizaci贸n feromona de arco {} -> {} = (1 - ) * {} +  * {} = {}\n",
+            "Actualización feromona de arco {} -> {} = (1 - ) * {} +  * {} = {} (aplicada al finalizar la colonia)\n",
             curr.to_char_index(),
             choosen.to_char_index(),
             pheromone,
             self.initial_pheromone,
-            self.pheromones[[curr, choosen]]
+            updated
         )?;
 
-        Ok(())
+        Ok((curr, choosen))
     }
 
-    fn build_solution<W: Write>(&mut self, ant: usize, out: &mut W) -> Result<Vec<usize>, Error> {
+    // Returns the tour, its local-update edges, and its own log buffer (see
+    // the comment on `run`).
+    fn build_solution(&self, ant: usize) -> Result<(Vec<usize>, Vec<(usize, usize)>, Vec<u8>), Error> {
+        let mut out = Vec::new();
         let mut rng = thread_rng();
-        let no_cities = self.visibility.shape()[0];
+        let no_cities = self.distances.size();
 
         let mut visited = Vec::new();
         visited.push(self.initial);
+        let mut local_updates = Vec::new();
 
         writeln!(out, "Hormiga {}", ant + 1)?;
         writeln!(out, "Ciudad inicial: {}", self.initial.to_char_index())?;
@@ -289,13 +519,16 @@ impl AntSystem {
             let q = rng.gen_range(0., 1.);
             writeln!(out, "Valor de q: {}", q)?;
 
-            if q <= self.q0 {
-                writeln!(out, "Recorrido por intensificaci贸n")?;
-                self.intesification(&mut visited, out)?;
+            let edge = if q <= self.q0 {
+                writeln!(out, "Recorrido por intensificación")?;
+                self.intesification(&visited, &mut out)?
             } else {
-                writeln!(out, "Recorrido por diversificaci贸n")?;
-                self.diversification(&mut visited, out)?;
-            }
+                writeln!(out, "Recorrido por diversificación")?;
+                self.diversification(&visited, &mut out)?
+            };
+
+            visited.push(edge.1);
+            local_updates.push(edge);
         }
 
         writeln!(
@@ -305,7 +538,7 @@ impl AntSystem {
             visited.to_display_path()?
         )?;
 
-        Ok(visited)
+        Ok((visited, local_updates, out))
     }
 
     fn update_pheromones<W: Write>(&mut self, out: &mut W) -> Result<(), Error> {
@@ -354,4 +587,59 @@ impl AntSystem {
 
         Ok(())
     }
+
+    /// Polish `tour` with 2-opt: repeatedly scan all pairs of non-adjacent
+    /// edges `(i, i+1)` and `(j, j+1)` and reverse the segment between them
+    /// whenever that lowers the total cost, until no improving swap is left.
+    ///
+    /// The before/after cost of a candidate swap is priced by re-summing the
+    /// whole affected segment rather than just its two boundary edges,
+    /// since reversing `tour[i+1..=j]` also reverses the direction of every
+    /// edge inside it — pricing only the boundary would silently assume a
+    /// symmetric distance matrix.
+    fn local_search<W: Write>(
+        distances: &DistanceSource,
+        tour: &mut Vec<usize>,
+        out: &mut W,
+    ) -> Result<(), Error> {
+        if tour.len() < 4 {
+            return Ok(());
+        }
+
+        loop {
+            let mut improved = false;
+
+            'search: for i in 0..tour.len() - 1 {
+                for j in (i + 2)..tour.len() - 1 {
+                    let segment = &tour[i..=j + 1];
+                    let before = compute_cost(segment, distances);
+
+                    let mut reversed = segment.to_vec();
+                    reversed[1..=j - i].reverse();
+                    let after = compute_cost(&reversed, distances);
+
+                    if after < before {
+                        writeln!(
+                            out,
+                            "2-opt: invertir {}..{} (costo {} -> {})",
+                            tour[i + 1].to_char_index(),
+                            tour[j].to_char_index(),
+                            before,
+                            after
+                        )?;
+
+                        tour[i + 1..=j].reverse();
+                        improved = true;
+                        break 'search;
+                    }
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+
+        Ok(())
+    }
 }